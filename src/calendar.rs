@@ -0,0 +1,149 @@
+//! External busy-calendar integration.
+//!
+//! A [`BusyCalendarSource`] fetches the busy intervals from a host's personal
+//! calendar so the slot engine can avoid double-booking. The only source
+//! implemented today reads an ICS feed over HTTP (the iCalendar export that
+//! CalDAV servers, Google Calendar and Outlook all expose at a secret URL).
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use opensase_scheduling::{SchedulingError, TimeSlot};
+
+/// A pluggable provider of a host's externally-occupied time ranges.
+#[async_trait]
+pub trait BusyCalendarSource: Send + Sync {
+    /// Fetch the current set of busy intervals, already normalised to UTC.
+    async fn fetch_busy(&self) -> Result<Vec<TimeSlot>, SchedulingError>;
+}
+
+/// Reads busy intervals from an ICS/iCalendar feed fetched over HTTP.
+pub struct IcsCalendarSource {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl IcsCalendarSource {
+    pub fn new(client: reqwest::Client, url: impl Into<String>) -> Self {
+        Self { client, url: url.into() }
+    }
+}
+
+#[async_trait]
+impl BusyCalendarSource for IcsCalendarSource {
+    async fn fetch_busy(&self) -> Result<Vec<TimeSlot>, SchedulingError> {
+        let body = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .map_err(|e| SchedulingError::CalendarSyncError(e.to_string()))?
+            .error_for_status()
+            .map_err(|e| SchedulingError::CalendarSyncError(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| SchedulingError::CalendarSyncError(e.to_string()))?;
+        Ok(parse_ics(&body))
+    }
+}
+
+/// Extract `VEVENT` start/end pairs from an ICS document as UTC [`TimeSlot`]s.
+///
+/// Only the common `DTSTART`/`DTEND` forms are handled: a UTC stamp
+/// (`...Z`), a `TZID=`-qualified local stamp resolved through its IANA zone,
+/// a floating local stamp (treated as UTC), and an all-day `VALUE=DATE`.
+/// Events whose `TZID` names an unknown zone, or that have no parseable
+/// start, are skipped rather than cached at the wrong instant.
+fn parse_ics(body: &str) -> Vec<TimeSlot> {
+    let mut slots = Vec::new();
+    let mut start: Option<DateTime<Utc>> = None;
+    let mut end: Option<DateTime<Utc>> = None;
+    let mut in_event = false;
+    for line in body.lines() {
+        let line = line.trim();
+        match line {
+            "BEGIN:VEVENT" => { in_event = true; start = None; end = None; }
+            "END:VEVENT" => {
+                if let (Some(s), Some(e)) = (start, end) {
+                    slots.push(TimeSlot { start: s, end: e, available: false });
+                }
+                in_event = false;
+            }
+            _ if in_event => {
+                if let Some((key, value)) = line.split_once(':') {
+                    let mut params = key.split(';');
+                    let prop = params.next().unwrap_or(key);
+                    let tzid = params.find_map(|p| p.strip_prefix("TZID="));
+                    match prop {
+                        "DTSTART" => start = parse_ics_datetime(value, tzid),
+                        "DTEND" => end = parse_ics_datetime(value, tzid),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    slots
+}
+
+fn parse_ics_datetime(value: &str, tzid: Option<&str>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Some(Utc.from_utc_datetime(&dt));
+    }
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        // A `TZID` parameter names the zone the local stamp is expressed in;
+        // resolve it so the instant is correct. Without one the stamp is
+        // floating and treated as UTC. An unknown zone is skipped rather
+        // than silently misplaced.
+        return match tzid {
+            Some(name) => {
+                let tz: Tz = name.parse().ok()?;
+                tz.from_local_datetime(&dt).single().map(|d| d.with_timezone(&Utc))
+            }
+            None => Some(Utc.from_utc_datetime(&dt)),
+        };
+    }
+    // All-day date: treat as midnight UTC.
+    chrono::NaiveDate::parse_from_str(value, "%Y%m%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| Utc.from_utc_datetime(&dt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vevents_into_utc_slots() {
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART:20240101T090000Z\r\nDTEND:20240101T093000Z\r\nEND:VEVENT\r\nEND:VCALENDAR";
+        let slots = parse_ics(ics);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].start, Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap());
+        assert_eq!(slots[0].end, Utc.with_ymd_and_hms(2024, 1, 1, 9, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn resolves_tzid_qualified_stamp_to_utc() {
+        let ics = "BEGIN:VEVENT\r\nDTSTART;TZID=America/New_York:20240101T090000\r\nDTEND;TZID=America/New_York:20240101T093000\r\nEND:VEVENT";
+        let slots = parse_ics(ics);
+        assert_eq!(slots.len(), 1);
+        // 09:00 EST is 14:00 UTC.
+        assert_eq!(slots[0].start, Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap());
+        assert_eq!(slots[0].end, Utc.with_ymd_and_hms(2024, 1, 1, 14, 30, 0).unwrap());
+    }
+
+    #[test]
+    fn skips_events_with_unknown_tzid() {
+        let ics = "BEGIN:VEVENT\r\nDTSTART;TZID=Mars/Olympus:20240101T090000\r\nDTEND;TZID=Mars/Olympus:20240101T093000\r\nEND:VEVENT";
+        assert!(parse_ics(ics).is_empty());
+    }
+
+    #[test]
+    fn skips_events_without_start() {
+        let ics = "BEGIN:VEVENT\r\nSUMMARY:broken\r\nEND:VEVENT";
+        assert!(parse_ics(ics).is_empty());
+    }
+}
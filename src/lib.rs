@@ -9,7 +9,8 @@
 //! - Team scheduling
 //! - Reminders and notifications
 
-use chrono::{DateTime, NaiveTime, Utc, Weekday};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
@@ -161,6 +162,14 @@ pub struct TimeSlot {
     pub available: bool,
 }
 
+impl TimeSlot {
+    /// Half-open overlap test: two slots collide when each starts before the
+    /// other ends. Touching at an endpoint (`a.end == b.start`) is not a clash.
+    pub fn overlaps(&self, other: &TimeSlot) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
 // =============================================================================
 // Error Types
 // =============================================================================
@@ -193,3 +202,211 @@ pub enum SchedulingError {
 }
 
 pub type Result<T> = std::result::Result<T, SchedulingError>;
+
+// =============================================================================
+// Availability Engine
+// =============================================================================
+
+/// Counts of existing bookings used to enforce [`BookingLimits`] caps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BookingCounts {
+    pub on_day: u32,
+    pub in_week: u32,
+}
+
+/// Compute bookable slots for `event` on `date`, honouring the schedule's
+/// weekly rules and date overrides, per-slot buffers, lead-time bounds and
+/// daily/weekly caps.
+///
+/// `existing` is every non-cancelled booking that could collide with the day,
+/// expressed in UTC; `now` is the current instant (injected so the function
+/// stays pure and testable). The schedule's `timezone` is interpreted as an
+/// IANA name, so the returned slots are correct UTC instants for invitees in
+/// any timezone.
+pub fn compute_available_slots(
+    schedule: &AvailabilitySchedule,
+    event: &EventType,
+    existing: &[TimeSlot],
+    date: NaiveDate,
+    now: DateTime<Utc>,
+    counts: BookingCounts,
+) -> Result<Vec<TimeSlot>> {
+    let tz: Tz = schedule
+        .timezone
+        .parse()
+        .map_err(|_| SchedulingError::StorageError(format!("unknown timezone: {}", schedule.timezone)))?;
+
+    let limits = &event.booking_limits;
+
+    // Whole-day suppression when the day or week is already fully booked.
+    if limits.max_per_day.is_some_and(|cap| counts.on_day >= cap)
+        || limits.max_per_week.is_some_and(|cap| counts.in_week >= cap)
+    {
+        return Ok(Vec::new());
+    }
+
+    // Pick the intervals that apply to this date: a matching override wins over
+    // the weekly rule, and an "unavailable" override blanks the day entirely.
+    let intervals: Vec<TimeInterval> = match schedule.overrides.iter().find(|o| o.date == date) {
+        Some(o) if o.is_unavailable => return Ok(Vec::new()),
+        Some(o) => o.intervals.clone(),
+        None => schedule
+            .rules
+            .iter()
+            .find(|r| r.day == date.weekday())
+            .map(|r| r.intervals.clone())
+            .unwrap_or_default(),
+    };
+
+    let earliest = now + Duration::hours(limits.min_notice_hours as i64);
+    let latest = now + Duration::days(limits.max_future_days as i64);
+    let step = (event.duration_minutes + event.buffer_after_minutes) as i64;
+    let duration = event.duration_minutes as i64;
+
+    let mut slots = Vec::new();
+    for interval in &intervals {
+        let start_min = minutes_since_midnight(interval.start);
+        let end_min = minutes_since_midnight(interval.end);
+        let mut cursor = start_min;
+        while cursor + duration <= end_min {
+            let local_start = NaiveDateTime::new(date, minute_to_time(cursor));
+            let local_end = NaiveDateTime::new(date, minute_to_time(cursor + duration));
+            cursor += step;
+
+            let (Some(start), Some(end)) = (
+                tz.from_local_datetime(&local_start).single(),
+                tz.from_local_datetime(&local_end).single(),
+            ) else {
+                // Skip slots that fall in a DST gap or are otherwise ambiguous.
+                continue;
+            };
+            let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+
+            if start < earliest || start > latest {
+                continue;
+            }
+
+            // A booking blocks this slot if the slot overlaps the booking once
+            // that booking has been padded by the configured buffers.
+            let available = !existing.iter().any(|b| {
+                let padded = TimeSlot {
+                    start: b.start - Duration::minutes(event.buffer_before_minutes as i64),
+                    end: b.end + Duration::minutes(event.buffer_after_minutes as i64),
+                    available: false,
+                };
+                TimeSlot { start, end, available: true }.overlaps(&padded)
+            });
+
+            slots.push(TimeSlot { start, end, available });
+        }
+    }
+
+    Ok(slots)
+}
+
+fn minutes_since_midnight(t: NaiveTime) -> i64 {
+    t.signed_duration_since(NaiveTime::MIN).num_minutes()
+}
+
+fn minute_to_time(minutes: i64) -> NaiveTime {
+    NaiveTime::MIN + Duration::minutes(minutes)
+}
+
+#[cfg(test)]
+mod availability_tests {
+    use super::*;
+
+    fn schedule(tz: &str) -> AvailabilitySchedule {
+        AvailabilitySchedule {
+            id: "sch_1".into(),
+            name: "Weekdays".into(),
+            timezone: tz.into(),
+            rules: vec![AvailabilityRule {
+                day: Weekday::Mon,
+                intervals: vec![TimeInterval {
+                    start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+                    end: NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+                }],
+            }],
+            overrides: vec![],
+        }
+    }
+
+    fn event(duration: u32, before: u32, after: u32) -> EventType {
+        EventType {
+            id: "evt_1".into(),
+            name: "Intro".into(),
+            description: None,
+            duration_minutes: duration,
+            buffer_before_minutes: before,
+            buffer_after_minutes: after,
+            color: "#3788d8".into(),
+            location: EventLocation::Phone,
+            availability_schedule_id: "sch_1".into(),
+            booking_limits: BookingLimits { min_notice_hours: 0, max_future_days: 365, ..Default::default() },
+            questions: vec![],
+            confirmations: ConfirmationSettings::default(),
+            is_active: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn emits_slots_respecting_duration_and_buffer() {
+        // Monday 2024-01-01, UTC, 30-minute event with a 10-minute after-buffer:
+        // slots begin every 40 minutes within 09:00-11:00 that still fit.
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let slots = compute_available_slots(&schedule("UTC"), &event(30, 0, 10), &[], date, now, BookingCounts::default()).unwrap();
+        let starts: Vec<_> = slots.iter().map(|s| s.start.format("%H:%M").to_string()).collect();
+        assert_eq!(starts, ["09:00", "09:40", "10:20"]);
+    }
+
+    #[test]
+    fn marks_overlapping_booking_unavailable() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let booked = TimeSlot {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap(),
+            end: Utc.with_ymd_and_hms(2024, 1, 1, 9, 30, 0).unwrap(),
+            available: false,
+        };
+        let slots = compute_available_slots(&schedule("UTC"), &event(30, 0, 0), &[booked], date, now, BookingCounts::default()).unwrap();
+        assert!(!slots[0].available);
+        assert!(slots[1].available);
+    }
+
+    #[test]
+    fn future_date_within_horizon_has_slots() {
+        // A week out with a 60-day horizon still yields the day's slots; the
+        // HTTP layer must pass a real horizon rather than the zeroed default.
+        let date = NaiveDate::from_ymd_opt(2024, 1, 8).unwrap(); // Monday
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut ev = event(30, 0, 0);
+        ev.booking_limits.max_future_days = 60;
+        let slots = compute_available_slots(&schedule("UTC"), &ev, &[], date, now, BookingCounts::default()).unwrap();
+        assert!(!slots.is_empty());
+    }
+
+    #[test]
+    fn zero_horizon_hides_future_slots() {
+        // Regression: a zero `max_future_days` collapses the horizon to `now`,
+        // so every future slot is filtered out. This is the bug the handler hit.
+        let date = NaiveDate::from_ymd_opt(2024, 1, 8).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut ev = event(30, 0, 0);
+        ev.booking_limits.max_future_days = 0;
+        let slots = compute_available_slots(&schedule("UTC"), &ev, &[], date, now, BookingCounts::default()).unwrap();
+        assert!(slots.is_empty());
+    }
+
+    #[test]
+    fn unavailable_override_blanks_the_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut sch = schedule("UTC");
+        sch.overrides.push(DateOverride { date, intervals: vec![], is_unavailable: true });
+        let slots = compute_available_slots(&sch, &event(30, 0, 0), &[], date, now, BookingCounts::default()).unwrap();
+        assert!(slots.is_empty());
+    }
+}
@@ -1,9 +1,15 @@
 //! OpenSASE Scheduling - Self-hosted Appointment Scheduling
 
+mod calendar;
+mod notifications;
+
 use anyhow::Result;
 use axum::{extract::{Path, Query, State}, http::StatusCode, routing::{get, post, put, delete}, Json, Router};
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use opensase_scheduling::{AvailabilityRule, AvailabilitySchedule, BookingCounts, BookingLimits, BookingQuestion, ConfirmationSettings, DateOverride, EventLocation, EventType as DomainEventType, QuestionType, TimeInterval, TimeSlot as DomainTimeSlot};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use sqlx::postgres::PgPoolOptions;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -13,17 +19,61 @@ use uuid::Uuid;
 pub struct Service {
     pub id: Uuid, pub name: String, pub description: Option<String>,
     pub duration_minutes: i32, pub price: Option<i64>, pub currency: String,
-    pub status: String, pub created_at: DateTime<Utc>,
+    pub status: String, pub assignment_strategy: String,
+    pub send_confirmation_email: bool, pub send_reminder_email: bool, pub reminder_hours_before: Vec<i32>,
+    pub min_notice_hours: i32, pub max_future_days: i32, pub max_per_day: Option<i32>, pub max_per_week: Option<i32>,
+    pub buffer_before_minutes: i32, pub buffer_after_minutes: i32,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Appointment {
-    pub id: Uuid, pub service_id: Uuid, pub customer_name: String, pub customer_email: String,
+    pub id: Uuid, pub service_id: Uuid, pub host_id: Option<Uuid>, pub customer_name: String, pub customer_email: String,
     pub customer_phone: Option<String>, pub scheduled_date: NaiveDate, pub scheduled_time: NaiveTime,
     pub duration_minutes: i32, pub status: String, pub notes: Option<String>,
     pub created_at: DateTime<Utc>, pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServiceHost {
+    pub service_id: Uuid, pub host_id: Uuid, pub availability_schedule_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct CalendarConnection {
+    pub id: Uuid, pub host_id: Uuid, pub source_type: String, pub url: String,
+    pub credentials: Option<String>, pub last_synced_at: Option<DateTime<Utc>>, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: Uuid, pub url: String, pub event_types: Vec<String>, pub secret: String, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServiceQuestion {
+    pub id: Uuid, pub service_id: Uuid, pub question: String, pub question_type: String,
+    pub required: bool, pub options: Vec<String>, pub position: i32, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AppointmentResponse {
+    pub id: Uuid, pub appointment_id: Uuid, pub question_id: Uuid, pub answer: String, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: Uuid, pub appointment_id: Uuid, pub kind: String, pub channel: String,
+    pub send_at: DateTime<Utc>, pub status: String, pub sent_at: Option<DateTime<Utc>>, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BusyBlock {
+    pub id: Uuid, pub host_id: Uuid, pub source_id: Uuid,
+    pub starts_at: DateTime<Utc>, pub ends_at: DateTime<Utc>, pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Availability {
     pub id: Uuid, pub day_of_week: i32, pub start_time: NaiveTime, pub end_time: NaiveTime,
@@ -38,17 +88,36 @@ async fn main() -> Result<()> {
     tracing_subscriber::registry().with(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into())).with(tracing_subscriber::fmt::layer()).init();
     let db = PgPoolOptions::new().max_connections(10).connect(&std::env::var("DATABASE_URL")?).await?;
     sqlx::migrate!("./migrations").run(&db).await?;
-    let state = AppState { db };
+    let state = AppState { db: db.clone() };
+
+    // Keep external busy calendars mirrored into `busy_blocks`.
+    let sync_interval = std::env::var("CALENDAR_SYNC_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300);
+    tokio::spawn(calendar_sync_loop(db.clone(), sync_interval));
+
+    // Dispatch due confirmation/reminder notifications in the background.
+    match notifications::SmtpNotifier::from_env() {
+        Ok(notifier) => { tokio::spawn(notification_worker(db.clone(), std::sync::Arc::new(notifier))); }
+        Err(e) => tracing::warn!("notification worker disabled: {e}"),
+    }
+
+    // Deliver outbox events to registered webhook subscribers.
+    tokio::spawn(webhook_dispatcher(db));
 
     let app = Router::new()
         .route("/health", get(|| async { Json(serde_json::json!({"status": "healthy", "service": "opensase-scheduling"})) }))
         .route("/api/v1/services", get(list_services).post(create_service))
         .route("/api/v1/services/:id", get(get_service).put(update_service).delete(delete_service))
+        .route("/api/v1/services/:id/hosts", post(add_service_host))
+        .route("/api/v1/services/:id/hosts/:host_id", delete(remove_service_host))
         .route("/api/v1/appointments", get(list_appointments).post(create_appointment))
         .route("/api/v1/appointments/:id", get(get_appointment).put(update_appointment))
         .route("/api/v1/appointments/:id/cancel", post(cancel_appointment))
+        .route("/api/v1/appointments/:id/notifications", get(list_notifications))
         .route("/api/v1/availability", get(get_availability).post(set_availability))
+        .route("/api/v1/date-overrides", post(set_date_override))
         .route("/api/v1/slots", get(get_available_slots))
+        .route("/api/v1/hosts/:id/calendars", post(add_calendar))
+        .route("/api/v1/webhooks", post(create_webhook))
         .layer(TraceLayer::new_for_http()).layer(CorsLayer::permissive()).with_state(state);
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "8088".to_string());
@@ -70,27 +139,246 @@ async fn get_service(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<
     sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = $1").bind(id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.map(Json).ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))
 }
 
-#[derive(Debug, Deserialize)] pub struct CreateServiceRequest { pub name: String, pub description: Option<String>, pub duration_minutes: i32, pub price: Option<i64> }
+/// Default booking horizon when a service does not specify one; without it the
+/// engine would treat `max_future_days = 0` as "no future bookings".
+fn default_max_future_days() -> i32 { 60 }
+
+#[derive(Debug, Deserialize)] pub struct CreateServiceRequest { pub name: String, pub description: Option<String>, pub duration_minutes: i32, pub price: Option<i64>, #[serde(default)] pub min_notice_hours: i32, #[serde(default = "default_max_future_days")] pub max_future_days: i32, pub max_per_day: Option<i32>, pub max_per_week: Option<i32>, #[serde(default)] pub buffer_before_minutes: i32, #[serde(default)] pub buffer_after_minutes: i32, #[serde(default)] pub questions: Vec<BookingQuestion> }
 
 async fn create_service(State(s): State<AppState>, Json(r): Json<CreateServiceRequest>) -> Result<(StatusCode, Json<Service>), (StatusCode, String)> {
-    let svc = sqlx::query_as::<_, Service>("INSERT INTO services (id, name, description, duration_minutes, price, currency, status, created_at) VALUES ($1, $2, $3, $4, $5, 'NGN', 'active', NOW()) RETURNING *")
-        .bind(Uuid::now_v7()).bind(&r.name).bind(&r.description).bind(r.duration_minutes).bind(r.price)
-        .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let svc = sqlx::query_as::<_, Service>("INSERT INTO services (id, name, description, duration_minutes, price, currency, status, min_notice_hours, max_future_days, max_per_day, max_per_week, buffer_before_minutes, buffer_after_minutes, created_at) VALUES ($1, $2, $3, $4, $5, 'NGN', 'active', $6, $7, $8, $9, $10, $11, NOW()) RETURNING *")
+        .bind(Uuid::now_v7()).bind(&r.name).bind(&r.description).bind(r.duration_minutes).bind(r.price).bind(r.min_notice_hours).bind(r.max_future_days).bind(r.max_per_day).bind(r.max_per_week).bind(r.buffer_before_minutes).bind(r.buffer_after_minutes)
+        .fetch_one(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    insert_questions(&mut tx, svc.id, &r.questions).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok((StatusCode::CREATED, Json(svc)))
 }
 
 async fn update_service(State(s): State<AppState>, Path(id): Path<Uuid>, Json(r): Json<CreateServiceRequest>) -> Result<Json<Service>, (StatusCode, String)> {
-    let svc = sqlx::query_as::<_, Service>("UPDATE services SET name = $2, description = $3, duration_minutes = $4, price = $5 WHERE id = $1 RETURNING *")
-        .bind(id).bind(&r.name).bind(&r.description).bind(r.duration_minutes).bind(r.price)
-        .fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let svc = sqlx::query_as::<_, Service>("UPDATE services SET name = $2, description = $3, duration_minutes = $4, price = $5, min_notice_hours = $6, max_future_days = $7, max_per_day = $8, max_per_week = $9, buffer_before_minutes = $10, buffer_after_minutes = $11 WHERE id = $1 RETURNING *")
+        .bind(id).bind(&r.name).bind(&r.description).bind(r.duration_minutes).bind(r.price).bind(r.min_notice_hours).bind(r.max_future_days).bind(r.max_per_day).bind(r.max_per_week).bind(r.buffer_before_minutes).bind(r.buffer_after_minutes)
+        .fetch_optional(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+    sqlx::query("DELETE FROM service_questions WHERE service_id = $1").bind(id).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    insert_questions(&mut tx, id, &r.questions).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(svc))
 }
 
+/// Canonical storage label for a [`QuestionType`], matching the enum variants.
+fn question_type_label(t: &QuestionType) -> &'static str {
+    match t {
+        QuestionType::ShortText => "ShortText",
+        QuestionType::LongText => "LongText",
+        QuestionType::SingleChoice => "SingleChoice",
+        QuestionType::MultipleChoice => "MultipleChoice",
+        QuestionType::Phone => "Phone",
+    }
+}
+
+async fn insert_questions(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, service_id: Uuid, questions: &[BookingQuestion]) -> Result<(), sqlx::Error> {
+    for (i, q) in questions.iter().enumerate() {
+        sqlx::query("INSERT INTO service_questions (id, service_id, question, question_type, required, options, position) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(Uuid::now_v7()).bind(service_id).bind(&q.question).bind(question_type_label(&q.question_type)).bind(q.required).bind(&q.options).bind(i as i32)
+            .execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+/// Validate invitee answers against a service's questions, returning a message
+/// describing the first problem found.
+fn validate_responses(questions: &[ServiceQuestion], responses: &HashMap<String, String>) -> Result<(), String> {
+    for q in questions {
+        let answer = responses.get(&q.id.to_string()).map(|s| s.trim()).filter(|s| !s.is_empty());
+        let Some(answer) = answer else {
+            if q.required { return Err(format!("Question '{}' is required", q.question)); }
+            continue;
+        };
+        match q.question_type.as_str() {
+            "SingleChoice" => {
+                if !q.options.iter().any(|o| o == answer) {
+                    return Err(format!("'{answer}' is not a valid option for '{}'", q.question));
+                }
+            }
+            "MultipleChoice" => {
+                for choice in answer.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+                    if !q.options.iter().any(|o| o == choice) {
+                        return Err(format!("'{choice}' is not a valid option for '{}'", q.question));
+                    }
+                }
+            }
+            "Phone" => {
+                let digits = answer.chars().filter(|c| c.is_ascii_digit()).count();
+                if digits < 7 || !answer.chars().all(|c| c.is_ascii_digit() || "+-() ".contains(c)) {
+                    return Err(format!("'{}' does not look like a valid phone number", q.question));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
 async fn delete_service(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<StatusCode, (StatusCode, String)> {
     sqlx::query("UPDATE services SET status = 'deleted' WHERE id = $1").bind(id).execute(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(StatusCode::NO_CONTENT)
 }
 
+// Service host endpoints
+#[derive(Debug, Deserialize)] pub struct AddHostRequest { pub host_id: Uuid, pub availability_schedule_id: Option<Uuid> }
+
+async fn add_service_host(State(s): State<AppState>, Path(id): Path<Uuid>, Json(r): Json<AddHostRequest>) -> Result<(StatusCode, Json<ServiceHost>), (StatusCode, String)> {
+    let host = sqlx::query_as::<_, ServiceHost>("INSERT INTO service_hosts (service_id, host_id, availability_schedule_id) VALUES ($1, $2, $3) ON CONFLICT (service_id, host_id) DO UPDATE SET availability_schedule_id = $3 RETURNING *")
+        .bind(id).bind(r.host_id).bind(r.availability_schedule_id)
+        .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(host)))
+}
+
+async fn remove_service_host(State(s): State<AppState>, Path((id, host_id)): Path<(Uuid, Uuid)>) -> Result<StatusCode, (StatusCode, String)> {
+    sqlx::query("DELETE FROM service_hosts WHERE service_id = $1 AND host_id = $2").bind(id).bind(host_id).execute(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Calendar integration endpoints
+#[derive(Debug, Deserialize)] pub struct AddCalendarRequest { pub source_type: Option<String>, pub url: String, pub credentials: Option<String> }
+
+async fn add_calendar(State(s): State<AppState>, Path(host_id): Path<Uuid>, Json(r): Json<AddCalendarRequest>) -> Result<(StatusCode, Json<CalendarConnection>), (StatusCode, String)> {
+    let conn = sqlx::query_as::<_, CalendarConnection>("INSERT INTO calendar_connections (id, host_id, source_type, url, credentials) VALUES ($1, $2, $3, $4, $5) RETURNING *")
+        .bind(Uuid::now_v7()).bind(host_id).bind(r.source_type.as_deref().unwrap_or("ics")).bind(&r.url).bind(&r.credentials)
+        .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(conn)))
+}
+
+/// Periodically re-import every connected calendar into `busy_blocks`.
+async fn calendar_sync_loop(db: sqlx::PgPool, interval_secs: u64) {
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = sync_all_calendars(&db, &client).await {
+            tracing::error!("calendar sync failed: {e}");
+        }
+    }
+}
+
+async fn sync_all_calendars(db: &sqlx::PgPool, client: &reqwest::Client) -> opensase_scheduling::Result<()> {
+    use calendar::{BusyCalendarSource, IcsCalendarSource};
+    let conns = sqlx::query_as::<_, CalendarConnection>("SELECT * FROM calendar_connections")
+        .fetch_all(db).await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+    for conn in conns {
+        let source = IcsCalendarSource::new(client.clone(), &conn.url);
+        let busy = match source.fetch_busy().await {
+            Ok(b) => b,
+            // Surface the failure but keep the previously-cached blocks for this host.
+            Err(e) => { tracing::warn!("calendar {} sync error: {e}", conn.id); continue; }
+        };
+        let mut tx = db.begin().await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+        sqlx::query("DELETE FROM busy_blocks WHERE source_id = $1").bind(conn.id).execute(&mut *tx).await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+        for slot in &busy {
+            sqlx::query("INSERT INTO busy_blocks (id, host_id, source_id, starts_at, ends_at) VALUES ($1, $2, $3, $4, $5)")
+                .bind(Uuid::now_v7()).bind(conn.host_id).bind(conn.id).bind(slot.start).bind(slot.end)
+                .execute(&mut *tx).await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+        }
+        sqlx::query("UPDATE calendar_connections SET last_synced_at = NOW() WHERE id = $1").bind(conn.id).execute(&mut *tx).await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+        tx.commit().await.map_err(|e| opensase_scheduling::SchedulingError::StorageError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+// Webhook + outbox
+#[derive(Debug, Deserialize)] pub struct CreateWebhookRequest { pub url: String, #[serde(default)] pub event_types: Vec<String>, pub secret: String }
+
+async fn create_webhook(State(s): State<AppState>, Json(r): Json<CreateWebhookRequest>) -> Result<(StatusCode, Json<Webhook>), (StatusCode, String)> {
+    let wh = sqlx::query_as::<_, Webhook>("INSERT INTO webhooks (id, url, event_types, secret) VALUES ($1, $2, $3, $4) RETURNING *")
+        .bind(Uuid::now_v7()).bind(&r.url).bind(&r.event_types).bind(&r.secret)
+        .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(wh)))
+}
+
+/// Append a domain event to the outbox inside the caller's transaction, so the
+/// event is committed atomically with the state change it describes.
+async fn write_outbox(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, event_type: &str, appt: &Appointment) -> Result<(), sqlx::Error> {
+    let payload = serde_json::json!({
+        "appointment_id": appt.id,
+        "service_id": appt.service_id,
+        "host_id": appt.host_id,
+        "status": appt.status,
+        "scheduled_date": appt.scheduled_date,
+        "scheduled_time": appt.scheduled_time,
+    });
+    sqlx::query("INSERT INTO outbox_events (id, event_type, payload) VALUES ($1, $2, $3)")
+        .bind(Uuid::now_v7()).bind(event_type).bind(payload).execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// Poll the outbox and deliver each event to every matching subscriber,
+/// signing the body and backing off exponentially on failure.
+async fn webhook_dispatcher(db: sqlx::PgPool) {
+    let client = reqwest::Client::new();
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(15));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = dispatch_webhooks(&db, &client).await {
+            tracing::error!("webhook dispatch failed: {e}");
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct OutboxEvent { id: Uuid, event_type: String, payload: serde_json::Value }
+
+async fn dispatch_webhooks(db: &sqlx::PgPool, client: &reqwest::Client) -> Result<(), sqlx::Error> {
+    const MAX_ATTEMPTS: i32 = 8;
+    let webhooks = sqlx::query_as::<_, Webhook>("SELECT * FROM webhooks").fetch_all(db).await?;
+    for wh in &webhooks {
+        // Events matching this subscriber's filter that still need delivery.
+        let events: Vec<OutboxEvent> = sqlx::query_as(
+            "SELECT e.id, e.event_type, e.payload FROM outbox_events e \
+             LEFT JOIN webhook_deliveries d ON d.event_id = e.id AND d.webhook_id = $1 \
+             WHERE (cardinality($2::text[]) = 0 OR e.event_type = ANY($2)) \
+             AND (d.id IS NULL OR (d.delivered = FALSE AND d.attempts < $3 AND d.next_attempt_at <= NOW())) \
+             ORDER BY e.created_at LIMIT 100")
+            .bind(wh.id).bind(&wh.event_types).bind(MAX_ATTEMPTS).fetch_all(db).await?;
+
+        for ev in events {
+            let body = serde_json::json!({ "id": ev.id, "event_type": ev.event_type, "payload": ev.payload }).to_string();
+            let signature = sign_payload(&wh.secret, &body);
+            let result = client.post(&wh.url)
+                .header("Content-Type", "application/json")
+                .header("X-Signature", format!("sha256={signature}"))
+                .body(body)
+                .send().await;
+            let status = result.as_ref().ok().map(|r| r.status().as_u16() as i32);
+            let ok = result.map(|r| r.status().is_success()).unwrap_or(false);
+
+            if ok {
+                sqlx::query("INSERT INTO webhook_deliveries (id, event_id, webhook_id, attempts, delivered, last_status, next_attempt_at, updated_at) \
+                    VALUES ($1, $2, $3, 1, TRUE, $4, NOW(), NOW()) \
+                    ON CONFLICT (event_id, webhook_id) DO UPDATE SET delivered = TRUE, attempts = webhook_deliveries.attempts + 1, last_status = $4, updated_at = NOW()")
+                    .bind(Uuid::now_v7()).bind(ev.id).bind(wh.id).bind(status).execute(db).await?;
+            } else {
+                // Exponential backoff: 2^attempts seconds before the next try.
+                sqlx::query("INSERT INTO webhook_deliveries (id, event_id, webhook_id, attempts, delivered, last_status, next_attempt_at, updated_at) \
+                    VALUES ($1, $2, $3, 1, FALSE, $4, NOW() + INTERVAL '2 seconds', NOW()) \
+                    ON CONFLICT (event_id, webhook_id) DO UPDATE SET attempts = webhook_deliveries.attempts + 1, last_status = $4, \
+                        next_attempt_at = NOW() + (INTERVAL '1 second' * POWER(2, webhook_deliveries.attempts + 1)), updated_at = NOW()")
+                    .bind(Uuid::now_v7()).bind(ev.id).bind(wh.id).bind(status).execute(db).await?;
+                tracing::warn!("webhook {} delivery to {} failed (status {:?})", wh.id, wh.url, status);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// HMAC-SHA256 of `body` keyed by the subscriber `secret`, hex-encoded.
+fn sign_payload(secret: &str, body: &str) -> String {
+    use hmac::{Hmac, Mac};
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect()
+}
+
 // Appointment endpoints
 async fn list_appointments(State(s): State<AppState>, Query(p): Query<ListParams>) -> Result<Json<Vec<Appointment>>, (StatusCode, String)> {
     let appts = if let Some(date) = p.date {
@@ -101,68 +389,425 @@ async fn list_appointments(State(s): State<AppState>, Query(p): Query<ListParams
     Ok(Json(appts))
 }
 
-async fn get_appointment(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<Appointment>, (StatusCode, String)> {
-    sqlx::query_as::<_, Appointment>("SELECT * FROM appointments WHERE id = $1").bind(id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.map(Json).ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))
+#[derive(Debug, Serialize)] pub struct AppointmentDetail { #[serde(flatten)] pub appointment: Appointment, pub responses: Vec<AppointmentResponse> }
+
+async fn get_appointment(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<AppointmentDetail>, (StatusCode, String)> {
+    let appointment = sqlx::query_as::<_, Appointment>("SELECT * FROM appointments WHERE id = $1").bind(id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+    let responses = sqlx::query_as::<_, AppointmentResponse>("SELECT * FROM appointment_responses WHERE appointment_id = $1 ORDER BY created_at").bind(id).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(AppointmentDetail { appointment, responses }))
 }
 
-#[derive(Debug, Deserialize)] pub struct CreateAppointmentRequest { pub service_id: Uuid, pub customer_name: String, pub customer_email: String, pub customer_phone: Option<String>, pub scheduled_date: NaiveDate, pub scheduled_time: NaiveTime, pub notes: Option<String> }
+#[derive(Debug, Deserialize)] pub struct CreateAppointmentRequest { pub service_id: Uuid, pub host_id: Option<Uuid>, pub customer_name: String, pub customer_email: String, pub customer_phone: Option<String>, pub scheduled_date: NaiveDate, pub scheduled_time: NaiveTime, pub notes: Option<String>, #[serde(default)] pub responses: HashMap<String, String> }
 
 async fn create_appointment(State(s): State<AppState>, Json(r): Json<CreateAppointmentRequest>) -> Result<(StatusCode, Json<Appointment>), (StatusCode, String)> {
     let svc = sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = $1").bind(r.service_id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::BAD_REQUEST, "Service not found".to_string()))?;
-    let appt = sqlx::query_as::<_, Appointment>("INSERT INTO appointments (id, service_id, customer_name, customer_email, customer_phone, scheduled_date, scheduled_time, duration_minutes, status, notes, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'confirmed', $9, NOW(), NOW()) RETURNING *")
-        .bind(Uuid::now_v7()).bind(r.service_id).bind(&r.customer_name).bind(&r.customer_email).bind(&r.customer_phone).bind(r.scheduled_date).bind(r.scheduled_time).bind(svc.duration_minutes).bind(&r.notes)
-        .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let hosts = sqlx::query_as::<_, ServiceHost>("SELECT * FROM service_hosts WHERE service_id = $1").bind(r.service_id).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Validate intake answers against the service's questions before booking.
+    let questions = sqlx::query_as::<_, ServiceQuestion>("SELECT * FROM service_questions WHERE service_id = $1 ORDER BY position").bind(r.service_id).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    validate_responses(&questions, &r.responses).map_err(|msg| (StatusCode::BAD_REQUEST, msg))?;
+
+    // Everything below must see a consistent view of the chosen host's day so
+    // two concurrent bookings cannot both grab the last open slot. A
+    // transaction-scoped advisory lock keyed on the instant serialises racing
+    // requests for the same date/time (so two services sharing a host can't
+    // both grab it); the unique index on appointments is the belt-and-braces
+    // backstop for a direct host collision.
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)").bind(slot_lock_key(r.scheduled_date, r.scheduled_time)).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Resolve the host: an explicit choice, round-robin to the least-loaded
+    // free host, or `None` for a collective event shared by all hosts.
+    let host_id = match r.host_id {
+        Some(h) => Some(h),
+        None if hosts.is_empty() => None,
+        None if svc.assignment_strategy == "round_robin" => {
+            let mut best: Option<(Uuid, i64)> = None;
+            for h in &hosts {
+                if host_has_conflict(&mut tx, h.host_id, r.scheduled_date, r.scheduled_time).await? { continue; }
+                let load: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM appointments WHERE host_id = $1 AND scheduled_date = $2 AND status != 'cancelled'")
+                    .bind(h.host_id).bind(r.scheduled_date).fetch_one(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+                if best.map(|(_, l)| load < l).unwrap_or(true) { best = Some((h.host_id, load)); }
+            }
+            Some(best.ok_or((StatusCode::CONFLICT, "No host available for this slot".to_string()))?.0)
+        }
+        None => None, // collective: the slot is held on every host's calendar
+    };
+
+    match host_id {
+        Some(h) => {
+            if host_has_conflict(&mut tx, h, r.scheduled_date, r.scheduled_time).await? {
+                return Err((StatusCode::CONFLICT, "Slot already taken for this host".to_string()));
+            }
+        }
+        // Collective: the slot is held on every host, so it must be free for
+        // all of them (and not already held by another collective booking).
+        None if !hosts.is_empty() => {
+            for h in &hosts {
+                if host_has_conflict(&mut tx, h.host_id, r.scheduled_date, r.scheduled_time).await? {
+                    return Err((StatusCode::CONFLICT, "Slot already taken for this host".to_string()));
+                }
+            }
+        }
+        None => {}
+    }
+
+    let appt = sqlx::query_as::<_, Appointment>("INSERT INTO appointments (id, service_id, host_id, customer_name, customer_email, customer_phone, scheduled_date, scheduled_time, duration_minutes, status, notes, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'confirmed', $10, NOW(), NOW()) RETURNING *")
+        .bind(Uuid::now_v7()).bind(r.service_id).bind(host_id).bind(&r.customer_name).bind(&r.customer_email).bind(&r.customer_phone).bind(r.scheduled_date).bind(r.scheduled_time).bind(svc.duration_minutes).bind(&r.notes)
+        .fetch_one(&mut *tx).await.map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.is_unique_violation() => (StatusCode::CONFLICT, "Slot already taken for this host".to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+    enqueue_notifications(&mut tx, &appt, &svc).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    write_outbox(&mut tx, "BookingCreated", &appt).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for q in &questions {
+        if let Some(answer) = r.responses.get(&q.id.to_string()).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            sqlx::query("INSERT INTO appointment_responses (id, appointment_id, question_id, answer) VALUES ($1, $2, $3, $4)")
+                .bind(Uuid::now_v7()).bind(appt.id).bind(q.id).bind(answer).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok((StatusCode::CREATED, Json(appt)))
 }
 
+/// Queue a confirmation (due immediately) plus a reminder per configured
+/// offset. Every message is queued on the email channel; nothing here queues
+/// SMS, and the notifier only logs SMS messages.
+async fn enqueue_notifications(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, appt: &Appointment, svc: &Service) -> Result<(), sqlx::Error> {
+    let channel = "email";
+    let scheduled = appt.scheduled_date.and_time(appt.scheduled_time).and_utc();
+    if svc.send_confirmation_email {
+        sqlx::query("INSERT INTO notifications (id, appointment_id, kind, channel, send_at) VALUES ($1, $2, 'confirmation', $3, NOW())")
+            .bind(Uuid::now_v7()).bind(appt.id).bind(channel).execute(&mut **tx).await?;
+    }
+    if svc.send_reminder_email {
+        for hours in &svc.reminder_hours_before {
+            let send_at = scheduled - chrono::Duration::hours(*hours as i64);
+            sqlx::query("INSERT INTO notifications (id, appointment_id, kind, channel, send_at) VALUES ($1, $2, 'reminder', $3, $4)")
+                .bind(Uuid::now_v7()).bind(appt.id).bind(channel).bind(send_at).execute(&mut **tx).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Derive a stable 64-bit advisory-lock key from a slot so that concurrent
+/// bookings for the same instant serialise in Postgres. Keyed on date/time
+/// only (not the service) so two services that share a host cannot each grab
+/// the same instant for that host in parallel.
+fn slot_lock_key(date: NaiveDate, time: NaiveTime) -> i64 {
+    use std::hash::{Hash, Hasher};
+    let mut h = std::collections::hash_map::DefaultHasher::new();
+    date.hash(&mut h);
+    time.hash(&mut h);
+    h.finish() as i64
+}
+
+/// Whether `host_id` is already committed at `date`/`time`. A booking blocks
+/// the host if it is booked directly against them, or if it is a collective
+/// booking (`host_id IS NULL`) on a service this host is assigned to — a
+/// collective booking occupies only the hosts of its own service.
+async fn host_has_conflict(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, host_id: Uuid, date: NaiveDate, time: NaiveTime) -> Result<bool, (StatusCode, String)> {
+    let n: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM appointments a WHERE a.scheduled_date = $2 AND a.scheduled_time = $3 AND a.status != 'cancelled' \
+         AND (a.host_id = $1 OR (a.host_id IS NULL AND EXISTS (SELECT 1 FROM service_hosts sh WHERE sh.service_id = a.service_id AND sh.host_id = $1)))")
+        .bind(host_id).bind(date).bind(time).fetch_one(&mut **tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(n > 0)
+}
+
 async fn update_appointment(State(s): State<AppState>, Path(id): Path<Uuid>, Json(r): Json<CreateAppointmentRequest>) -> Result<Json<Appointment>, (StatusCode, String)> {
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     let appt = sqlx::query_as::<_, Appointment>("UPDATE appointments SET scheduled_date = $2, scheduled_time = $3, notes = $4, updated_at = NOW() WHERE id = $1 RETURNING *")
         .bind(id).bind(r.scheduled_date).bind(r.scheduled_time).bind(&r.notes)
-        .fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+        .fetch_optional(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+    // Rescheduling moves the reminders: drop the pending ones and re-queue
+    // against the new time (the confirmation has already gone out).
+    let svc = sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = $1").bind(appt.service_id).fetch_one(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    sqlx::query("UPDATE notifications SET status = 'cancelled' WHERE appointment_id = $1 AND status = 'pending' AND kind = 'reminder'").bind(id).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if svc.send_reminder_email {
+        let channel = "email";
+        let scheduled = appt.scheduled_date.and_time(appt.scheduled_time).and_utc();
+        for hours in &svc.reminder_hours_before {
+            sqlx::query("INSERT INTO notifications (id, appointment_id, kind, channel, send_at) VALUES ($1, $2, 'reminder', $3, $4)")
+                .bind(Uuid::now_v7()).bind(id).bind(channel).bind(scheduled - chrono::Duration::hours(*hours as i64)).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        }
+    }
+    write_outbox(&mut tx, "BookingRescheduled", &appt).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(appt))
 }
 
 async fn cancel_appointment(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<Appointment>, (StatusCode, String)> {
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     let appt = sqlx::query_as::<_, Appointment>("UPDATE appointments SET status = 'cancelled', updated_at = NOW() WHERE id = $1 RETURNING *")
-        .bind(id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+        .bind(id).fetch_optional(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::NOT_FOUND, "Not found".to_string()))?;
+    sqlx::query("UPDATE notifications SET status = 'cancelled' WHERE appointment_id = $1 AND status = 'pending'").bind(id).execute(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    write_outbox(&mut tx, "BookingCancelled", &appt).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(appt))
 }
 
+async fn list_notifications(State(s): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<Vec<Notification>>, (StatusCode, String)> {
+    let rows = sqlx::query_as::<_, Notification>("SELECT * FROM notifications WHERE appointment_id = $1 ORDER BY send_at").bind(id).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(rows))
+}
+
+/// Poll for due, unsent notifications and dispatch them through the notifier.
+async fn notification_worker(db: sqlx::PgPool, notifier: std::sync::Arc<dyn notifications::Notifier>) {
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(30));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = dispatch_due_notifications(&db, notifier.as_ref()).await {
+            tracing::error!("notification dispatch failed: {e}");
+        }
+    }
+}
+
+async fn dispatch_due_notifications(db: &sqlx::PgPool, notifier: &dyn notifications::Notifier) -> Result<(), sqlx::Error> {
+    use notifications::{Channel, Outbound};
+    let due: Vec<Notification> = sqlx::query_as("SELECT * FROM notifications WHERE status = 'pending' AND send_at <= NOW() ORDER BY send_at LIMIT 100").fetch_all(db).await?;
+    for n in due {
+        let Some(appt) = sqlx::query_as::<_, Appointment>("SELECT * FROM appointments WHERE id = $1").bind(n.appointment_id).fetch_optional(db).await? else { continue };
+        if appt.status == "cancelled" { continue; }
+        let channel = Channel::parse(&n.channel);
+        let to = match channel { Channel::Sms => appt.customer_phone.clone().unwrap_or_default(), Channel::Email => appt.customer_email.clone() };
+        let subject = if n.kind == "confirmation" { "Your appointment is confirmed" } else { "Appointment reminder" };
+        let body = format!("Hi {}, this is a {} for your appointment on {} at {}.", appt.customer_name, n.kind, appt.scheduled_date, appt.scheduled_time);
+        let message = Outbound { channel, to, subject: subject.to_string(), body };
+        match notifier.send(&message).await {
+            Ok(()) => {
+                let mut tx = db.begin().await?;
+                sqlx::query("UPDATE notifications SET status = 'sent', sent_at = NOW() WHERE id = $1").bind(n.id).execute(&mut *tx).await?;
+                if n.kind == "reminder" {
+                    write_outbox(&mut tx, "ReminderSent", &appt).await?;
+                    tracing::info!("reminder sent for appointment {}", appt.id);
+                }
+                tx.commit().await?;
+            }
+            Err(e) => {
+                tracing::warn!("notification {} failed: {e}", n.id);
+                sqlx::query("UPDATE notifications SET status = 'failed' WHERE id = $1").bind(n.id).execute(db).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
 // Availability endpoints
 async fn get_availability(State(s): State<AppState>) -> Result<Json<Vec<Availability>>, (StatusCode, String)> {
     let avail = sqlx::query_as::<_, Availability>("SELECT * FROM availability ORDER BY day_of_week, start_time").fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(avail))
 }
 
-#[derive(Debug, Deserialize)] pub struct SetAvailabilityRequest { pub day_of_week: i32, pub start_time: NaiveTime, pub end_time: NaiveTime, pub is_available: bool }
+#[derive(Debug, Deserialize)] pub struct SetAvailabilityRequest { #[serde(default)] pub schedule_id: Option<Uuid>, pub day_of_week: i32, pub start_time: NaiveTime, pub end_time: NaiveTime, pub is_available: bool }
 
 async fn set_availability(State(s): State<AppState>, Json(r): Json<SetAvailabilityRequest>) -> Result<(StatusCode, Json<Availability>), (StatusCode, String)> {
-    let avail = sqlx::query_as::<_, Availability>("INSERT INTO availability (id, day_of_week, start_time, end_time, is_available, created_at) VALUES ($1, $2, $3, $4, $5, NOW()) ON CONFLICT (day_of_week) DO UPDATE SET start_time = $3, end_time = $4, is_available = $5 RETURNING *")
-        .bind(Uuid::now_v7()).bind(r.day_of_week).bind(r.start_time).bind(r.end_time).bind(r.is_available)
+    // `schedule_id` scopes the row to a host's own schedule; NULL is the shared,
+    // service-wide schedule. Upsert on (schedule, weekday, start) so a host can
+    // keep distinct hours and a day can carry more than one interval.
+    let avail = sqlx::query_as::<_, Availability>("INSERT INTO availability (id, schedule_id, day_of_week, start_time, end_time, is_available, created_at) VALUES ($1, $2, $3, $4, $5, $6, NOW()) ON CONFLICT (COALESCE(schedule_id, '00000000-0000-0000-0000-000000000000'::uuid), day_of_week, start_time) DO UPDATE SET end_time = $5, is_available = $6 RETURNING *")
+        .bind(Uuid::now_v7()).bind(r.schedule_id).bind(r.day_of_week).bind(r.start_time).bind(r.end_time).bind(r.is_available)
         .fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok((StatusCode::OK, Json(avail)))
 }
 
-#[derive(Debug, Deserialize)] pub struct SlotsQuery { pub date: NaiveDate, pub service_id: Uuid }
-#[derive(Debug, Serialize)] pub struct TimeSlot { pub time: NaiveTime, pub available: bool }
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DateOverrideRow {
+    pub id: Uuid, pub schedule_id: Option<Uuid>, pub override_date: NaiveDate,
+    pub start_time: Option<NaiveTime>, pub end_time: Option<NaiveTime>,
+    pub is_unavailable: bool, pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)] pub struct SetDateOverrideRequest { pub schedule_id: Option<Uuid>, pub date: NaiveDate, #[serde(default)] pub intervals: Vec<TimeInterval>, #[serde(default)] pub is_unavailable: bool }
+
+/// Record a holiday or one-off change for a single date on a schedule:
+/// `is_unavailable` blanks the day, otherwise `intervals` replace the weekly
+/// rule for that date. Re-posting the same date replaces the prior override.
+async fn set_date_override(State(s): State<AppState>, Json(r): Json<SetDateOverrideRequest>) -> Result<(StatusCode, Json<Vec<DateOverrideRow>>), (StatusCode, String)> {
+    if !r.is_unavailable && r.intervals.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "override must set is_unavailable or supply intervals".to_string()));
+    }
+    let mut tx = s.db.begin().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    match r.schedule_id {
+        Some(sid) => sqlx::query("DELETE FROM date_overrides WHERE schedule_id = $1 AND override_date = $2").bind(sid).bind(r.date).execute(&mut *tx).await,
+        None => sqlx::query("DELETE FROM date_overrides WHERE schedule_id IS NULL AND override_date = $1").bind(r.date).execute(&mut *tx).await,
+    }.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut rows = Vec::new();
+    if r.is_unavailable {
+        let row = sqlx::query_as::<_, DateOverrideRow>("INSERT INTO date_overrides (id, schedule_id, override_date, is_unavailable, created_at) VALUES ($1, $2, $3, true, NOW()) RETURNING *")
+            .bind(Uuid::now_v7()).bind(r.schedule_id).bind(r.date).fetch_one(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        rows.push(row);
+    } else {
+        for iv in &r.intervals {
+            let row = sqlx::query_as::<_, DateOverrideRow>("INSERT INTO date_overrides (id, schedule_id, override_date, start_time, end_time, is_unavailable, created_at) VALUES ($1, $2, $3, $4, $5, false, NOW()) RETURNING *")
+                .bind(Uuid::now_v7()).bind(r.schedule_id).bind(r.date).bind(iv.start).bind(iv.end).fetch_one(&mut *tx).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            rows.push(row);
+        }
+    }
+    tx.commit().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok((StatusCode::CREATED, Json(rows)))
+}
+
+#[derive(Debug, Deserialize)] pub struct SlotsQuery { pub date: NaiveDate, pub service_id: Uuid, pub tz: Option<String> }
+#[derive(Debug, Serialize)] pub struct TimeSlot { pub start: DateTime<Utc>, pub end: DateTime<Utc>, pub available: bool }
+
+// Postgres `day_of_week` uses 0 = Sunday through 6 = Saturday.
+fn weekday_from_dow(dow: i32) -> Weekday {
+    match dow.rem_euclid(7) {
+        0 => Weekday::Sun, 1 => Weekday::Mon, 2 => Weekday::Tue, 3 => Weekday::Wed,
+        4 => Weekday::Thu, 5 => Weekday::Fri, _ => Weekday::Sat,
+    }
+}
+
+/// Build a weekly [`AvailabilitySchedule`] from the availability table,
+/// collapsing the per-day rows into a single rule per weekday. `schedule_id`
+/// selects a host's own working hours; `None` selects the shared, service-wide
+/// rows (`schedule_id IS NULL`).
+async fn build_schedule(db: &sqlx::PgPool, schedule_id: Option<Uuid>, svc: &Service, timezone: &str) -> Result<AvailabilitySchedule, (StatusCode, String)> {
+    let avail = match schedule_id {
+        Some(sid) => sqlx::query_as::<_, Availability>("SELECT * FROM availability WHERE is_available = true AND schedule_id = $1 ORDER BY day_of_week, start_time").bind(sid).fetch_all(db).await,
+        None => sqlx::query_as::<_, Availability>("SELECT * FROM availability WHERE is_available = true AND schedule_id IS NULL ORDER BY day_of_week, start_time").fetch_all(db).await,
+    }.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut rules: Vec<AvailabilityRule> = Vec::new();
+    for a in &avail {
+        let day = weekday_from_dow(a.day_of_week);
+        let interval = TimeInterval { start: a.start_time, end: a.end_time };
+        match rules.iter_mut().find(|r| r.day == day) {
+            Some(r) => r.intervals.push(interval),
+            None => rules.push(AvailabilityRule { day, intervals: vec![interval] }),
+        }
+    }
+
+    // Fold the per-date override rows into one `DateOverride` per date: a
+    // whole-day block wins, otherwise the rows' intervals replace the weekly rule.
+    let override_rows = match schedule_id {
+        Some(sid) => sqlx::query_as::<_, DateOverrideRow>("SELECT * FROM date_overrides WHERE schedule_id = $1 ORDER BY override_date, start_time").bind(sid).fetch_all(db).await,
+        None => sqlx::query_as::<_, DateOverrideRow>("SELECT * FROM date_overrides WHERE schedule_id IS NULL ORDER BY override_date, start_time").fetch_all(db).await,
+    }.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut overrides: Vec<DateOverride> = Vec::new();
+    for row in &override_rows {
+        let entry = match overrides.iter_mut().find(|o| o.date == row.override_date) {
+            Some(o) => o,
+            None => { overrides.push(DateOverride { date: row.override_date, intervals: vec![], is_unavailable: false }); overrides.last_mut().unwrap() }
+        };
+        if row.is_unavailable { entry.is_unavailable = true; }
+        if let (Some(start), Some(end)) = (row.start_time, row.end_time) { entry.intervals.push(TimeInterval { start, end }); }
+    }
+
+    Ok(AvailabilitySchedule { id: schedule_id.map(|s| s.to_string()).unwrap_or_else(|| svc.id.to_string()), name: svc.name.clone(), timezone: timezone.to_string(), rules, overrides })
+}
 
 async fn get_available_slots(State(s): State<AppState>, Query(q): Query<SlotsQuery>) -> Result<Json<Vec<TimeSlot>>, (StatusCode, String)> {
     let svc = sqlx::query_as::<_, Service>("SELECT * FROM services WHERE id = $1").bind(q.service_id).fetch_optional(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?.ok_or((StatusCode::BAD_REQUEST, "Service not found".to_string()))?;
-    
-    // Get booked appointments for the date
+
+    let timezone = q.tz.clone().unwrap_or_else(|| "UTC".to_string());
+    let tz: Tz = timezone.parse().map_err(|_| (StatusCode::BAD_REQUEST, format!("unknown timezone: {timezone}")))?;
+
+    let event = DomainEventType {
+        id: svc.id.to_string(), name: svc.name.clone(), description: svc.description.clone(),
+        duration_minutes: svc.duration_minutes as u32,
+        buffer_before_minutes: svc.buffer_before_minutes.max(0) as u32, buffer_after_minutes: svc.buffer_after_minutes.max(0) as u32,
+        color: "#3788d8".into(), location: EventLocation::Custom { instructions: String::new() },
+        availability_schedule_id: svc.id.to_string(),
+        booking_limits: BookingLimits {
+            min_notice_hours: svc.min_notice_hours.max(0) as u32,
+            max_future_days: svc.max_future_days.max(0) as u32,
+            max_per_day: svc.max_per_day.map(|v| v.max(0) as u32),
+            max_per_week: svc.max_per_week.map(|v| v.max(0) as u32),
+        },
+        questions: vec![], confirmations: ConfirmationSettings::default(), is_active: svc.status == "active",
+        created_at: svc.created_at,
+    };
+
     let booked: Vec<Appointment> = sqlx::query_as("SELECT * FROM appointments WHERE scheduled_date = $1 AND status != 'cancelled'")
         .bind(q.date).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    
-    // Generate slots (9 AM to 5 PM, every 30 minutes)
-    let mut slots = Vec::new();
-    let mut time = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
-    let end = NaiveTime::from_hms_opt(17, 0, 0).unwrap();
-    
-    while time < end {
-        let is_booked = booked.iter().any(|a| a.scheduled_time == time);
-        slots.push(TimeSlot { time, available: !is_booked });
-        time = time + chrono::Duration::minutes(svc.duration_minutes as i64);
-    }
-    
-    Ok(Json(slots))
+
+    // Externally-busy intervals imported from connected calendars, bounded to a
+    // window that brackets the requested day in any timezone.
+    let window_start = (q.date - chrono::Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let window_end = (q.date + chrono::Duration::days(2)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let busy: Vec<BusyBlock> = sqlx::query_as("SELECT * FROM busy_blocks WHERE ends_at > $1 AND starts_at < $2")
+        .bind(window_start).bind(window_end).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Membership of every service that has a collective (host-less) booking
+    // on the requested date, so such a booking blocks only the hosts of its own service.
+    let collective_services: Vec<Uuid> = booked.iter().filter(|a| a.host_id.is_none()).map(|a| a.service_id).collect();
+    let mut collective_hosts: HashMap<Uuid, std::collections::HashSet<Uuid>> = HashMap::new();
+    if !collective_services.is_empty() {
+        let rows = sqlx::query_as::<_, ServiceHost>("SELECT * FROM service_hosts WHERE service_id = ANY($1)").bind(&collective_services).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        for r in rows { collective_hosts.entry(r.service_id).or_default().insert(r.host_id); }
+    }
+
+    // Expand non-cancelled bookings plus external busy blocks into UTC slots the
+    // engine can test for overlap. `for_host = None` is the single-calendar case
+    // (every booking on the date blocks, whatever its service); a host id restricts to that
+    // host's commitments across all services, plus collective bookings of any
+    // service the host belongs to.
+    let expand = |for_host: Option<Uuid>| -> Vec<DomainTimeSlot> {
+        let keep = |a: &Appointment| -> bool {
+            match for_host {
+                // Single shared calendar (no hosts configured): every booking
+                // occupies the one resource, so all of them block.
+                None => true,
+                Some(h) => a.host_id == Some(h)
+                    || (a.host_id.is_none() && collective_hosts.get(&a.service_id).is_some_and(|hs| hs.contains(&h))),
+            }
+        };
+        let mut out: Vec<DomainTimeSlot> = booked.iter().filter(|a| keep(a)).filter_map(|a| {
+            let start = tz.from_local_datetime(&NaiveDateTime::new(a.scheduled_date, a.scheduled_time)).single()?;
+            Some(DomainTimeSlot {
+                start: start.with_timezone(&Utc),
+                end: (start + chrono::Duration::minutes(a.duration_minutes as i64)).with_timezone(&Utc),
+                available: false,
+            })
+        }).collect();
+        out.extend(busy.iter().filter(|b| for_host.is_none() || Some(b.host_id) == for_host)
+            .map(|b| DomainTimeSlot { start: b.starts_at, end: b.ends_at, available: false }));
+        out
+    };
+
+    let week_start = q.date - chrono::Duration::days(q.date.weekday().num_days_from_monday() as i64);
+    let on_day = booked.iter().filter(|a| a.service_id == svc.id).count() as u32;
+    let in_week: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM appointments WHERE service_id = $1 AND scheduled_date >= $2 AND scheduled_date < $3 AND status != 'cancelled'")
+        .bind(svc.id).bind(week_start).bind(week_start + chrono::Duration::days(7)).fetch_one(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let counts = BookingCounts { on_day, in_week: in_week as u32 };
+    let now = Utc::now();
+
+    let hosts = sqlx::query_as::<_, ServiceHost>("SELECT * FROM service_hosts WHERE service_id = $1").bind(svc.id).fetch_all(&s.db).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let slots: Vec<DomainTimeSlot> = if hosts.is_empty() {
+        let schedule = build_schedule(&s.db, None, &svc, &timezone).await?;
+        opensase_scheduling::compute_available_slots(&schedule, &event, &expand(None), q.date, now, counts).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    } else {
+        // Compute each host's day against that host's *own* working hours, then
+        // combine by slot start: `collective` offers a slot only when every host
+        // is free, `round_robin` when any host is free. Keying by start (rather
+        // than zipping positionally) keeps the merge correct when hosts keep
+        // different schedules and so produce different candidate grids.
+        let collective = svc.assignment_strategy != "round_robin";
+        let mut free_by_start: std::collections::BTreeMap<DateTime<Utc>, (DateTime<Utc>, usize)> = std::collections::BTreeMap::new();
+        let mut schedule_cache: HashMap<Option<Uuid>, AvailabilitySchedule> = HashMap::new();
+        for h in &hosts {
+            let schedule = match schedule_cache.get(&h.availability_schedule_id) {
+                Some(sched) => sched.clone(),
+                None => {
+                    let sched = build_schedule(&s.db, h.availability_schedule_id, &svc, &timezone).await?;
+                    schedule_cache.insert(h.availability_schedule_id, sched.clone());
+                    sched
+                }
+            };
+            let per = opensase_scheduling::compute_available_slots(&schedule, &event, &expand(Some(h.host_id)), q.date, now, counts).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            for slot in per {
+                let entry = free_by_start.entry(slot.start).or_insert((slot.end, 0));
+                if slot.available { entry.1 += 1; }
+            }
+        }
+        free_by_start.into_iter().map(|(start, (end, free_hosts))| {
+            // A host that does not even offer the slot contributes 0, so the
+            // collective test naturally fails when one host is off that day.
+            let available = if collective { free_hosts == hosts.len() } else { free_hosts > 0 };
+            DomainTimeSlot { start, end, available }
+        }).collect()
+    };
+
+    Ok(Json(slots.into_iter().map(|s| TimeSlot { start: s.start, end: s.end, available: s.available }).collect()))
 }
@@ -0,0 +1,78 @@
+//! Outbound notification delivery.
+//!
+//! The scheduler enqueues confirmation and reminder rows; a background worker
+//! polls for due messages and hands each to a [`Notifier`]. Email is delivered
+//! over SMTP; SMS is a stub until a gateway is wired up.
+
+use async_trait::async_trait;
+use opensase_scheduling::SchedulingError;
+
+/// A message ready to be delivered on a particular channel.
+#[derive(Clone, Debug)]
+pub struct Outbound {
+    pub channel: Channel,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel { Email, Sms }
+
+impl Channel {
+    pub fn as_str(&self) -> &'static str {
+        match self { Channel::Email => "email", Channel::Sms => "sms" }
+    }
+    pub fn parse(s: &str) -> Channel {
+        if s == "sms" { Channel::Sms } else { Channel::Email }
+    }
+}
+
+/// Delivers an [`Outbound`] message, or reports why it could not be sent.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, message: &Outbound) -> Result<(), SchedulingError>;
+}
+
+/// Sends email over SMTP and logs SMS as a placeholder for a future gateway.
+pub struct SmtpNotifier {
+    transport: lettre::SmtpTransport,
+    from: String,
+}
+
+impl SmtpNotifier {
+    /// Build a notifier from `SMTP_URL` (e.g. `smtp://localhost:1025`) and
+    /// `NOTIFY_FROM`; falls back to a local unauthenticated relay.
+    pub fn from_env() -> Result<Self, SchedulingError> {
+        let url = std::env::var("SMTP_URL").unwrap_or_else(|_| "smtp://localhost:25".to_string());
+        let transport = lettre::SmtpTransport::from_url(&url)
+            .map_err(|e| SchedulingError::StorageError(e.to_string()))?
+            .build();
+        let from = std::env::var("NOTIFY_FROM").unwrap_or_else(|_| "no-reply@opensase.local".to_string());
+        Ok(Self { transport, from })
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn send(&self, message: &Outbound) -> Result<(), SchedulingError> {
+        match message.channel {
+            Channel::Email => {
+                use lettre::Transport;
+                let email = lettre::Message::builder()
+                    .from(self.from.parse().map_err(|e: lettre::address::AddressError| SchedulingError::StorageError(e.to_string()))?)
+                    .to(message.to.parse().map_err(|e: lettre::address::AddressError| SchedulingError::StorageError(e.to_string()))?)
+                    .subject(message.subject.clone())
+                    .body(message.body.clone())
+                    .map_err(|e| SchedulingError::StorageError(e.to_string()))?;
+                self.transport.send(&email).map_err(|e| SchedulingError::StorageError(e.to_string()))?;
+                Ok(())
+            }
+            Channel::Sms => {
+                // TODO: route through an SMS gateway once one is configured.
+                tracing::info!("SMS to {}: {}", message.to, message.body);
+                Ok(())
+            }
+        }
+    }
+}